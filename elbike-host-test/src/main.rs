@@ -43,9 +43,32 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Mirrors `Controller2BCParcer`'s frame checksum (a CRC-8, poly 0x07, over
+/// raw_data[1..6] chained with raw_data[7..10]) so these hand-built test
+/// frames pass its validation instead of being silently dropped.
+fn frame_checksum(raw_data: &[u8; 12]) -> u8 {
+    raw_data[1..6]
+        .iter()
+        .chain(raw_data[7..10].iter())
+        .fold(0u8, |crc, &b| crc8_update(crc, b))
+}
+
+fn crc8_update(crc: u8, byte: u8) -> u8 {
+    let mut crc = crc ^ byte;
+    for _ in 0..8 {
+        crc = if crc & 0x80 != 0 {
+            (crc << 1) ^ 0x07
+        } else {
+            crc << 1
+        };
+    }
+    crc
+}
+
 fn test_simple(port: &mut dyn SerialPort) -> Result<()> {
-    const SRC: [u8; 12] = [0x41, 0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0];
-    port.write_all(&SRC)?;
+    let mut src: [u8; 12] = [0x41, 0, 0x30, 0, 0, 0, 0, 0, 42, 0, 0, 0];
+    src[6] = frame_checksum(&src);
+    port.write_all(&src)?;
     port.flush()?;
 
     std::thread::sleep(Duration::from_millis(1000));
@@ -64,6 +87,8 @@ fn draw_sinus(port: &mut dyn SerialPort) -> Result<()> {
 
     for i in 0..128 {
         template[8] = (92.0 * (2.0 * std::f32::consts::PI * i as f32 / 128.0).sin().abs()) as u8;
+        template[2] = 0x30;
+        template[6] = frame_checksum(&template);
 
         port.write_all(&template)?;
         port.flush()?;
@@ -9,10 +9,30 @@ use eeprom24x::{
     Eeprom24x, Error,
 };
 
+#[repr(C)]
 #[derive(Copy, Clone)]
 struct StorageItem<T: Copy> {
     pub block_counter: usize,
     pub item: T,
+    /// Adler-32 checksum over the raw bytes of `block_counter` and
+    /// `item`, so a record torn by a power loss mid-`page_write` can be
+    /// told apart from a genuine one.
+    checksum: u32,
+    /// Padding so the record stays a power-of-two size (the `init` scan
+    /// assumes `StorageItem<T>` evenly tiles a 256-byte region).
+    _reserved: u32,
+}
+
+/// A small Adler-32 checksum: enough to catch a torn write without
+/// pulling in a CRC crate for a no_std target.
+fn checksum_bytes(bytes: &[u8]) -> u32 {
+    let mut sum1: u32 = 1;
+    let mut sum2: u32 = 0;
+    for &b in bytes {
+        sum1 = (sum1 + b as u32) % 65521;
+        sum2 = (sum2 + sum1) % 65521;
+    }
+    (sum2 << 16) | sum1
 }
 
 pub struct EepromLog<T: Copy, I2C, PS, AS> {
@@ -73,26 +93,64 @@ where
         let mut res: StorageItem<T> = unsafe { core::mem::MaybeUninit::uninit().assume_init() };
 
         unsafe {
-            let res = core::slice::from_raw_parts_mut(
+            let raw = core::slice::from_raw_parts_mut(
                 &mut res as *mut _ as *mut u8,
                 core::mem::size_of::<StorageItem<T>>(),
             );
 
             self.eeprom.read_data(
                 (offset * core::mem::size_of::<StorageItem<T>>()) as u32,
-                res,
+                raw,
             )?;
+        }
+
+        if !Self::is_valid(&res) {
+            // A torn write (e.g. a brownout mid-page_write) can leave a
+            // record with a bogus block_counter/item pair; treat it exactly
+            // like an empty/unwritten slot instead of mistaking it for live
+            // data.
+            return Ok(StorageItem {
+                block_counter: usize::MAX,
+                item: T::default(),
+                checksum: 0,
+                _reserved: 0,
+            });
+        }
+
+        Ok(res)
+    }
 
-            Ok(*(res.as_ptr() as *const StorageItem<T>))
+    fn is_valid(item: &StorageItem<T>) -> bool {
+        if item.block_counter == usize::MAX {
+            // erased flash reads back as all-ones; an erased slot is
+            // valid-empty and was never checksummed.
+            return true;
         }
+        checksum_bytes(Self::checked_bytes(item)) == item.checksum
+    }
+
+    /// The `block_counter`/`item` bytes to checksum, derived from the
+    /// actual field offsets (rather than a literal `size_of::<usize>() +
+    /// size_of::<T>()`) so a `T` whose alignment forces `#[repr(C)]`
+    /// padding before `item` doesn't throw the slice off by the padding
+    /// width.
+    fn checked_bytes(item: &StorageItem<T>) -> &[u8] {
+        let base = item as *const StorageItem<T> as *const u8;
+        let checksum_ptr = &item.checksum as *const u32 as *const u8;
+        let len = checksum_ptr as usize - base as usize;
+        unsafe { core::slice::from_raw_parts(base, len) }
     }
 
     pub fn last(&mut self) -> Result<T, eeprom24x::Error<E>> {
-        if self.current_block_offset > 0 {
-            let current = self.read(self.current_block_offset.wrapping_sub(1))?;
+        let mut offset = self.current_block_offset;
+        while offset > 0 {
+            offset -= 1;
+            let current = self.read(offset)?;
             if current.block_counter != usize::MAX {
-                return Ok(current.item)
+                return Ok(current.item);
             }
+            // Checksum-invalid record (surfaced by `read` as block_counter ==
+            // MAX): keep walking backward to the last genuinely valid one.
         }
         Ok(T::default()) // no valid data empty flash
     }
@@ -100,10 +158,13 @@ where
     pub fn append(&mut self, val: T) -> Result<usize, eeprom24x::Error<E>> {
         let mut address =
             (self.current_block_offset * core::mem::size_of::<StorageItem<T>>()) as u32;
-        let data = StorageItem::<T> {
+        let mut data = StorageItem::<T> {
             block_counter: self.current_block_counter,
             item: val,
+            checksum: 0,
+            _reserved: 0,
         };
+        data.checksum = checksum_bytes(Self::checked_bytes(&data));
 
         let data = unsafe {
             core::slice::from_raw_parts(
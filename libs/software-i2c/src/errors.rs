@@ -0,0 +1,18 @@
+/// Errors that can occur while driving the software i2c bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The addressed device did not acknowledge the last byte sent.
+    NoAck,
+    /// Another master on the bus won arbitration: we drove SDA high but
+    /// read it back low while SCL was high.
+    ArbitrationLoss,
+    /// SDA or SCL was already found stuck low before a START condition
+    /// could be emitted.
+    BusError,
+    /// A slave held SCL low (clock stretching) past the allotted timeout.
+    Timeout,
+    /// A 10-bit address was outside the valid `0x000..=0x3FF` range.
+    AddressOutOfRange,
+    /// Any other bus failure not covered by the variants above.
+    Other,
+}
@@ -15,7 +15,7 @@ mod single_client;
 mod errors;
 
 pub use shared_i2c_bus::SharedI2CBus;
-pub use single_client::I2C;
+pub use single_client::{Operation, I2C};
 pub use nanosecond_delay::ProvideNanosecondDelay;
 pub use errors::Error;
 
@@ -24,3 +24,29 @@ pub enum I2CSpeed {
     Fast400kHz = 1250,
     Normal100kHz = 2500,
 }
+
+/// A target device address, either 7-bit or 10-bit.
+#[derive(Clone, Copy)]
+pub enum Address {
+    /// A standard 7-bit address (0x00..=0x7F).
+    Bit7(u8),
+    /// An extended 10-bit address (0x000..=0x3FF).
+    Bit10(u16),
+}
+
+impl From<u8> for Address {
+    fn from(address: u8) -> Self {
+        Address::Bit7(address)
+    }
+}
+
+/// Controls the ratio between SCL low time and SCL high time in fast mode,
+/// mirroring the duty-cycle option on the stm32f1 HAL.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DutyCycle {
+    /// SCL low time equals SCL high time.
+    Ratio2to1,
+    /// SCL low time is 16/9 of SCL high time, giving boards with weak
+    /// pull-ups more time for the line to rise before the next high phase.
+    Ratio16to9,
+}
@@ -5,7 +5,10 @@ use embedded_hal::{
     digital::v2::StatefulOutputPin,
 };
 
-use crate::{nanosecond_delay::ProvideNanosecondDelay, single_client::I2C};
+use crate::{
+    nanosecond_delay::ProvideNanosecondDelay,
+    single_client::{Operation, I2C},
+};
 
 pub struct SharedI2CBus<SDA, SCL, DP>(UnsafeCell<I2C<SDA, SCL, DP>>)
 where
@@ -54,13 +57,7 @@ where
     fn write(&mut self, address: u8, bytes: &[u8]) -> core::result::Result<(), Self::Error> {
         let bus = self.0 .0.get();
 
-        unsafe {
-            (*bus).begin_transmission(address, true)?;
-            (*bus).write(bytes)?;
-            (*bus).end_transmission();
-        }
-
-        Ok(())
+        unsafe { (*bus).transaction(address, &mut [Operation::Write(bytes)]) }
     }
 }
 
@@ -78,24 +75,13 @@ where
         bytes: &[u8],
         buffer: &mut [u8],
     ) -> core::result::Result<(), Self::Error> {
-        let mut res = Ok(());
-
         let bus = self.0 .0.get();
+
         unsafe {
-            (*bus).begin_transmission(address, true)?;
-            (*bus).write(bytes)?;
-            for place in buffer.iter_mut() {
-                match (*bus).read(true) {
-                    Ok(v) => *place = v,
-                    Err(e) => {
-                        res = Err(e);
-                        break;
-                    }
-                }
-            }
-            (*bus).end_transmission();
+            (*bus).transaction(
+                address,
+                &mut [Operation::Write(bytes), Operation::Read(buffer)],
+            )
         }
-
-        res
     }
 }
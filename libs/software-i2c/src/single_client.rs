@@ -2,15 +2,55 @@ use embedded_hal::{
     blocking::i2c::{Write, WriteRead},
     digital::v2::StatefulOutputPin,
 };
-use esp8266_hal::{
-    ram,
-    time::{Nanoseconds, U32Ext},
-};
+use esp8266_hal::{ram, time::Nanoseconds};
 
-use crate::{nanosecond_delay::ProvideNanosecondDelay, I2CSpeed};
+use crate::{nanosecond_delay::ProvideNanosecondDelay, Address, DutyCycle, I2CSpeed};
 
 type Result<T> = core::result::Result<T, crate::errors::Error>;
 
+/// Bus timing derived from the selected [`I2CSpeed`]/[`DutyCycle`], modeled
+/// on the embassy `Timings::new` helper.
+#[derive(Clone, Copy)]
+struct Timings {
+    /// Nominal SCL/SDA high half-period, in nanoseconds.
+    half_period_ns: u32,
+    /// SCL low period, in nanoseconds; lengthened relative to
+    /// `half_period_ns` when `DutyCycle::Ratio16to9` is selected.
+    low_period_ns: u32,
+    /// Setup/hold delay applied around SDA transitions.
+    data_delay_ns: u32,
+}
+
+impl Timings {
+    fn new(speed: I2CSpeed, duty_cycle: DutyCycle) -> Self {
+        let half_period_ns = match speed {
+            I2CSpeed::Normal100kHz => 2500,
+            I2CSpeed::Fast400kHz => 625,
+        };
+
+        let low_period_ns = match (speed, duty_cycle) {
+            (I2CSpeed::Fast400kHz, DutyCycle::Ratio16to9) => half_period_ns * 16 / 9,
+            _ => half_period_ns,
+        };
+
+        Self {
+            half_period_ns,
+            low_period_ns,
+            data_delay_ns: half_period_ns / 5,
+        }
+    }
+}
+
+/// A single i2c bus operation, as part of a [`I2C::transaction`].
+///
+/// Modeled on `embedded_hal::i2c::Operation`.
+pub enum Operation<'a> {
+    /// Read data into the given buffer.
+    Read(&'a mut [u8]),
+    /// Write the given bytes.
+    Write(&'a [u8]),
+}
+
 /// Represents a two-wire i2c controller.
 pub struct I2C<SDA, SCL, DP>
 where
@@ -24,8 +64,15 @@ where
     scl_pin: SCL,
     /// The speed at which to drive the clock signals.
     speed: I2CSpeed,
+    /// The SCL low/high time ratio to use in fast mode.
+    duty_cycle: DutyCycle,
+    /// Bus timing computed from `speed`/`duty_cycle`.
+    timings: Timings,
     /// provider for nanoseconds delay
     delay_provider: DP,
+    /// Whether a transaction is currently open, so the next
+    /// `begin_transmission` emits a repeated START instead of a START.
+    in_transaction: bool,
 }
 
 impl<SDA, SCL, DP> I2C<SDA, SCL, DP>
@@ -41,6 +88,9 @@ where
     /// This means you must have a pull-up resistor for each
     /// line on your circuit.
     ///
+    /// If SDA is found stuck low (e.g. a slave left mid-byte by a prior
+    /// reset), the bus is recovered before this returns.
+    ///
     /// ```
     /// let mut wire = I2C::Begin(        
     ///     pins.gpio2.into_open_drain_output(),
@@ -49,14 +99,22 @@ where
     /// );
     /// ```
     pub fn new(sda: SDA, scl: SCL, delay_provider: DP) -> Self {
+        let speed = I2CSpeed::Normal100kHz;
+        let duty_cycle = DutyCycle::Ratio2to1;
         let mut res = Self {
             sda_pin: sda,
             scl_pin: scl,
-            speed: I2CSpeed::Normal100kHz,
+            speed,
+            duty_cycle,
+            timings: Timings::new(speed, duty_cycle),
             delay_provider,
+            in_transaction: false,
         };
 
-        res.end_transmission();
+        // A slave left mid-byte by a previous reset (e.g. after a
+        // brownout) can be holding SDA low; clock it free before the
+        // firmware starts issuing transactions.
+        res.recover_bus();
 
         res
     }
@@ -68,36 +126,100 @@ where
     /// If the write_mode parameter is true, the R/W bit will
     /// be 0, signalling to the downstream devices that
     /// a write operation will follow.
+    ///
+    /// If a transmission is already open (i.e. this is called again
+    /// without an intervening `end_transmission`), a repeated START is
+    /// emitted instead of a START, as required when switching direction
+    /// mid-transaction (e.g. a register write followed by a data read).
+    ///
+    /// `address` accepts either a plain `u8` (treated as a 7-bit address)
+    /// or an [`Address`] when 10-bit addressing is needed. A 10-bit
+    /// address out of the `0x000..=0x3FF` range is rejected with
+    /// `AddressOutOfRange` before anything is driven on the bus.
     #[ram]
-    pub fn begin_transmission(&mut self, address: u8, write_mode: bool) -> Result<()> {
+    pub fn begin_transmission(
+        &mut self,
+        address: impl Into<Address>,
+        write_mode: bool,
+    ) -> Result<()> {
+        let address = address.into();
+        if let Address::Bit10(addr) = address {
+            if addr > 0x3ff {
+                return Err(crate::errors::Error::AddressOutOfRange);
+            }
+        }
+
         // Start transmission
-        i2c_start_condition(self);
+        if self.in_transaction {
+            i2c_repeated_start_condition(self)?;
+        } else {
+            i2c_start_condition(self)?;
+            self.in_transaction = true;
+        }
 
-        // Address frame
-        let mut mask = 0x1 << 6;
-        for _ in 0..=6 {
-            let high = address & mask;
-            i2c_write_bit(self, high > 0);
-            mask >>= 1;
+        let rw_bit = !write_mode as u8;
+
+        match address {
+            Address::Bit7(addr) => self.write_address_byte((addr << 1) | rw_bit)?,
+            Address::Bit10(addr) => {
+                // 10-bit addressing: 0b11110 | addr[9:8] | R/W, then the
+                // low 8 bits of the address as a second, plain byte. Per
+                // spec, a *read* must still send R/W=0 in this first
+                // phase (so the slave latches the combined address), then
+                // re-send the high byte with R/W=1 after a repeated START
+                // — a compliant 10-bit slave won't answer a read whose
+                // very first byte already carries R/W=1.
+                let high_byte = 0xf0 | (((addr >> 8) as u8) << 1);
+                self.write_address_byte(high_byte)?;
+                self.write_address_byte((addr & 0xff) as u8)?;
+
+                if !write_mode {
+                    i2c_repeated_start_condition(self)?;
+                    self.write_address_byte(high_byte | rw_bit)?;
+                }
+            }
         }
 
-        // R/W bit
-        if write_mode {
-            i2c_write_bit(self, false);
-        } else {
-            i2c_write_bit(self, true);
+        Ok(())
+    }
+
+    /// Writes a single address-frame byte and expects an ack, aborting
+    /// the transmission (STOP + clear `in_transaction`) on any error.
+    #[ram]
+    fn write_address_byte(&mut self, byte: u8) -> Result<()> {
+        let mut mask = 0x1 << 7;
+        for _ in 0..8 {
+            if let Err(e) = i2c_write_bit(self, byte & mask > 0) {
+                self.abort_transaction();
+                return Err(e);
+            }
+            mask >>= 1;
         }
 
         // Ack bit
-        let ack = i2c_read_bit(self);
-        if ack == false {
-            // Success
-            return Ok(());
-        } else {
+        let ack = match i2c_read_bit(self) {
+            Ok(ack) => ack,
+            Err(e) => {
+                self.abort_transaction();
+                return Err(e);
+            }
+        };
+        if ack {
             // Transmissino not acknowledged. Terminate.
-            i2c_end_condition(self);
+            self.abort_transaction();
             return Err(crate::errors::Error::NoAck);
         }
+
+        Ok(())
+    }
+
+    /// Ends the current transmission and clears `in_transaction`, used to
+    /// unwind the bus after a mid-transmission error (NACK, arbitration
+    /// loss, clock-stretch timeout).
+    #[ram]
+    fn abort_transaction(&mut self) {
+        i2c_end_condition(self);
+        self.in_transaction = false;
     }
 
     /// This method terminates an existing i2c transmission by
@@ -105,6 +227,49 @@ where
     #[ram]
     pub fn end_transmission(&mut self) {
         i2c_end_condition(self);
+        self.in_transaction = false;
+    }
+
+    /// Runs a sequence of read/write operations against a single device,
+    /// as a single i2c transaction.
+    ///
+    /// A repeated START is emitted before each operation whose direction
+    /// differs from the previous one (e.g. a register-address write
+    /// followed by a data read), and a single STOP is emitted once all
+    /// operations have completed.
+    ///
+    /// ```
+    /// let mut wire = I2C::new(...);
+    /// wire.transaction(0x50, &mut [
+    ///     Operation::Write(&[0x00]),
+    ///     Operation::Read(&mut buffer),
+    /// ]);
+    /// ```
+    #[ram]
+    pub fn transaction(
+        &mut self,
+        address: impl Into<Address>,
+        operations: &mut [Operation],
+    ) -> Result<()> {
+        let address = address.into();
+        let mut prev_write_mode: Option<bool> = None;
+
+        for operation in operations.iter_mut() {
+            let write_mode = matches!(operation, Operation::Write(_));
+            if prev_write_mode != Some(write_mode) {
+                self.begin_transmission(address, write_mode)?;
+            }
+            prev_write_mode = Some(write_mode);
+
+            match operation {
+                Operation::Write(bytes) => self.write(bytes)?,
+                Operation::Read(buffer) => self.read_bytes(buffer)?,
+            }
+        }
+
+        self.end_transmission();
+
+        Ok(())
     }
 
     /// This method will write a series of bytes to
@@ -127,15 +292,24 @@ where
             let mut mask = 0x1 << 7;
             for _ in 0..=7 {
                 let high = byte & mask;
-                i2c_write_bit(self, high > 0);
+                if let Err(e) = i2c_write_bit(self, high > 0) {
+                    self.abort_transaction();
+                    return Err(e);
+                }
                 mask >>= 1;
             }
-            let ack = i2c_read_bit(self);
+            let ack = match i2c_read_bit(self) {
+                Ok(ack) => ack,
+                Err(e) => {
+                    self.abort_transaction();
+                    return Err(e);
+                }
+            };
             if ack == false {
                 // Success
             } else {
                 // Not acknowledged
-                i2c_end_condition(self);
+                self.abort_transaction();
                 return Err(crate::errors::Error::NoAck);
             }
         }
@@ -170,7 +344,7 @@ where
         let mut mask = 0x1 << 7;
 
         for _ in 0..8 {
-            if i2c_read_bit(self) {
+            if i2c_read_bit(self)? {
                 byte |= mask;
             }
             mask >>= 1;
@@ -178,12 +352,97 @@ where
 
         if ack {
             // Send the ack bit
-            i2c_write_bit(self, false);
+            i2c_write_bit(self, false)?;
+        } else {
+            // Send the nack bit: release SDA high and clock it, so the
+            // slave sees a real NACK before the STOP/repeated-START that
+            // follows instead of being left thinking it was acked.
+            i2c_write_bit(self, true)?;
         }
 
         return Ok(byte);
     }
 
+    /// This method fills `buffer` from the downstream device, acking every
+    /// byte except the last one, which is NACKed as required by the i2c
+    /// spec before a STOP (or a repeated START switching direction) —
+    /// acking the final byte instead tells the device the master wants
+    /// more data, which can hang the transfer.
+    ///
+    /// In order to use this method successfully,
+    /// you must first have invoked `i2c.begin_transmission()`
+    #[ram]
+    pub fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<()> {
+        let last = buffer.len().wrapping_sub(1);
+        for (i, place) in buffer.iter_mut().enumerate() {
+            *place = self.read(i != last)?;
+        }
+
+        Ok(())
+    }
+
+    /// This method probes the bus for a device at `address`, issuing a
+    /// START and the address byte with R/W=0 without writing any data,
+    /// and reports whether it was acknowledged.
+    ///
+    /// ```
+    /// let mut wire = I2C::new(...);
+    /// if wire.probe(0x3c)? {
+    ///     // found the display
+    /// }
+    /// ```
+    pub fn probe(&mut self, address: u8) -> Result<bool> {
+        match self.begin_transmission(address, true) {
+            Ok(()) => {
+                self.end_transmission();
+                Ok(true)
+            }
+            Err(crate::errors::Error::NoAck) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// This method probes every valid 7-bit i2c address (0x08..=0x77,
+    /// excluding the reserved address ranges) and invokes `callback` with
+    /// the address and whether it was acknowledged. Handy for bringing up
+    /// a new board and confirming the SSD1306/EEPROM are wired correctly.
+    ///
+    /// ```
+    /// let mut wire = I2C::new(...);
+    /// wire.scan(|address, found| {
+    ///     if found {
+    ///         writeln!(serial, "found device at 0x{:02x}", address).ok();
+    ///     }
+    /// });
+    /// ```
+    pub fn scan<F: FnMut(u8, bool)>(&mut self, mut callback: F) {
+        for address in 0x08..=0x77 {
+            let found = self.probe(address).unwrap_or(false);
+            callback(address, found);
+        }
+    }
+
+    /// This method recovers a bus on which SDA is stuck low, e.g. because
+    /// a slave was reset mid-byte and is still holding the line down
+    /// waiting to finish a clock it never got. It releases SDA and
+    /// pulses SCL up to 9 times (enough to walk any partial byte out of
+    /// the slave's shift register) until SDA is observed high again,
+    /// then issues a STOP to leave the bus idle.
+    #[ram]
+    pub fn recover_bus(&mut self) {
+        data_release(self);
+
+        for _ in 0..9 {
+            if self.sda_pin.is_set_high().unwrap_or(true) {
+                break;
+            }
+            clock_low(self);
+            clock_release(self);
+        }
+
+        self.end_transmission();
+    }
+
     /// This method will change the signal speed.
     /// By default, all signals are clocked at 100kHz
     /// but if you upgrade to fast mode, it'll be 400kHz.
@@ -194,6 +453,23 @@ where
     /// ```
     pub fn set_speed(mut self, speed: I2CSpeed) -> Self {
         self.speed = speed;
+        self.timings = Timings::new(self.speed, self.duty_cycle);
+        self
+    }
+
+    /// This method changes the ratio between the SCL low time and the SCL
+    /// high time used in fast mode. Lengthening the low time relative to
+    /// the high time (`Ratio16to9`) gives boards with weak pull-ups more
+    /// time for SCL to actually rise before the next high phase.
+    ///
+    /// ```
+    /// let mut wire = I2C::new(...);
+    /// wire.set_speed(I2CSpeed::Fast400kHz);
+    /// wire.set_duty_cycle(DutyCycle::Ratio16to9);
+    /// ```
+    pub fn set_duty_cycle(mut self, duty_cycle: DutyCycle) -> Self {
+        self.duty_cycle = duty_cycle;
+        self.timings = Timings::new(self.speed, self.duty_cycle);
         self
     }
 }
@@ -206,7 +482,7 @@ where
     DP: ProvideNanosecondDelay,
 {
     let _ = i2c.scl_pin.set_high();
-    i2c.delay_provider.delay_ns(500.ns());
+    i2c.delay_provider.delay_ns(Nanoseconds(i2c.timings.half_period_ns));
 }
 
 #[ram]
@@ -217,7 +493,7 @@ where
     DP: ProvideNanosecondDelay,
 {
     let _ = i2c.scl_pin.set_low();
-    i2c.delay_provider.delay_ns(500.ns());
+    i2c.delay_provider.delay_ns(Nanoseconds(i2c.timings.low_period_ns));
 }
 
 #[ram]
@@ -228,7 +504,7 @@ where
     DP: ProvideNanosecondDelay,
 {
     let _ = i2c.sda_pin.set_high();
-    i2c.delay_provider.delay_ns(500.ns());
+    i2c.delay_provider.delay_ns(Nanoseconds(i2c.timings.data_delay_ns));
 }
 
 #[ram]
@@ -239,7 +515,7 @@ where
     DP: ProvideNanosecondDelay,
 {
     let _ = i2c.sda_pin.set_low();
-    i2c.delay_provider.delay_ns(500.ns());
+    i2c.delay_provider.delay_ns(Nanoseconds(i2c.timings.data_delay_ns));
 }
 
 #[ram]
@@ -250,7 +526,7 @@ where
     DP: ProvideNanosecondDelay,
 {
     let _ = i2c.sda_pin.set_high();
-    i2c.delay_provider.delay_ns(500.ns());
+    i2c.delay_provider.delay_ns(Nanoseconds(i2c.timings.data_delay_ns));
 }
 
 #[ram]
@@ -261,22 +537,50 @@ where
     DP: ProvideNanosecondDelay,
 {
     let _ = i2c.scl_pin.set_high();
-    i2c.delay_provider.delay_ns(500.ns());
+    i2c.delay_provider.delay_ns(Nanoseconds(i2c.timings.half_period_ns));
 }
 
 #[ram]
-fn i2c_start_condition<SDA, SCL, DP>(i2c: &mut I2C<SDA, SCL, DP>)
+fn i2c_start_condition<SDA, SCL, DP>(i2c: &mut I2C<SDA, SCL, DP>) -> Result<()>
 where
     SDA: StatefulOutputPin,
     SCL: StatefulOutputPin,
     DP: ProvideNanosecondDelay,
 {
+    // Both lines should be released (high) before we drive anything; if
+    // either is already stuck low, a slave or another master is holding
+    // the bus and we can't safely start a transmission.
+    if !i2c.sda_pin.is_set_high().unwrap_or_default()
+        || !i2c.scl_pin.is_set_high().unwrap_or_default()
+    {
+        return Err(crate::errors::Error::BusError);
+    }
+
     data_low(i2c);
     clock_low(i2c);
+
+    Ok(())
 }
 
+/// Emits a repeated START: release SDA, release SCL, then pull SDA low
+/// while SCL is high, before pulling SCL low again. Unlike
+/// `i2c_start_condition`, this does not assume the bus was already idle
+/// (SDA/SCL released), since the previous operation may have left SDA
+/// low (e.g. after acking a read byte).
 #[ram]
-fn i2c_read_bit<SDA, SCL, DP>(i2c: &mut I2C<SDA, SCL, DP>) -> bool
+fn i2c_repeated_start_condition<SDA, SCL, DP>(i2c: &mut I2C<SDA, SCL, DP>) -> Result<()>
+where
+    SDA: StatefulOutputPin,
+    SCL: StatefulOutputPin,
+    DP: ProvideNanosecondDelay,
+{
+    data_release(i2c);
+    clock_release(i2c);
+    i2c_start_condition(i2c)
+}
+
+#[ram]
+fn i2c_read_bit<SDA, SCL, DP>(i2c: &mut I2C<SDA, SCL, DP>) -> Result<bool>
 where
     SDA: StatefulOutputPin,
     SCL: StatefulOutputPin,
@@ -291,8 +595,8 @@ where
     clock_release(i2c);
 
     let nanos = i2c.delay_provider.nanos();
-    let timeout = wraping_add_nanos(nanos, Nanoseconds(i2c.speed as u32 * 4));
-    let stretch_timeout = wraping_add_nanos(nanos, Nanoseconds(i2c.speed as u32 * 16));
+    let timeout = wraping_add_nanos(nanos, Nanoseconds(i2c.timings.half_period_ns));
+    let stretch_timeout = wraping_add_nanos(nanos, Nanoseconds(i2c.timings.half_period_ns * 4));
     let mut res = true;
 
     loop {
@@ -302,7 +606,13 @@ where
         let clock_line = i2c.scl_pin.is_set_high().unwrap_or_default();
         let data_line = i2c.sda_pin.is_set_high().unwrap_or_default();
 
-        if clock_line == false && now < stretch_timeout {
+        if clock_line == false {
+            if now >= stretch_timeout {
+                // The slave has held SCL low past the allotted budget.
+                clock_low(i2c);
+                data_low(i2c);
+                return Err(crate::errors::Error::Timeout);
+            }
             // We are stretching the signal
             continue;
         } else if data_line == false {
@@ -313,18 +623,19 @@ where
             break;
         }
 
-        i2c.delay_provider.delay_ns(500.ns());
+        i2c.delay_provider
+            .delay_ns(Nanoseconds(i2c.timings.data_delay_ns));
     }
 
     // Bring clock back down
     clock_low(i2c);
     data_low(i2c);
 
-    res
+    Ok(res)
 }
 
 #[ram]
-fn i2c_write_bit<SDA, SCL, DP>(i2c: &mut I2C<SDA, SCL, DP>, high: bool)
+fn i2c_write_bit<SDA, SCL, DP>(i2c: &mut I2C<SDA, SCL, DP>, high: bool) -> Result<()>
 where
     SDA: StatefulOutputPin,
     SCL: StatefulOutputPin,
@@ -336,19 +647,22 @@ where
         data_low(i2c);
     }
 
-    // Wait
-    i2c.delay_provider.delay_ns(Nanoseconds(i2c.speed as u32));
-
     // **************
     // Pulse the clock
     // **************
     clock_release(i2c);
-    i2c.delay_provider
-        .delay_ns(Nanoseconds(i2c.speed as u32 * 2));
+
+    if high && !i2c.sda_pin.is_set_high().unwrap_or_default() {
+        // We released SDA high but another master is pulling it low:
+        // they won arbitration.
+        clock_low(i2c);
+        return Err(crate::errors::Error::ArbitrationLoss);
+    }
 
     // Pull clock low
     clock_low(i2c);
-    i2c.delay_provider.delay_ns(Nanoseconds(i2c.speed as u32));
+
+    Ok(())
 }
 
 #[ram]
@@ -359,9 +673,11 @@ where
     DP: ProvideNanosecondDelay,
 {
     clock_release(i2c);
-    i2c.delay_provider.delay_ns(500.ns());
+    i2c.delay_provider
+        .delay_ns(Nanoseconds(i2c.timings.half_period_ns));
     data_release(i2c);
-    i2c.delay_provider.delay_ns(500.ns());
+    i2c.delay_provider
+        .delay_ns(Nanoseconds(i2c.timings.data_delay_ns));
 }
 
 #[ram]
@@ -381,11 +697,7 @@ where
 
     #[ram]
     fn write(&mut self, address: u8, bytes: &[u8]) -> core::result::Result<(), Self::Error> {
-        Self::begin_transmission(self, address, true)?;
-        Self::write(self, bytes)?;
-        Self::end_transmission(self);
-
-        Ok(())
+        Self::transaction(self, address, &mut [Operation::Write(bytes)])
     }
 }
 
@@ -403,21 +715,10 @@ where
         bytes: &[u8],
         buffer: &mut [u8],
     ) -> core::result::Result<(), Self::Error> {
-        let mut res = Ok(());
-
-        Self::begin_transmission(self, address, true)?;
-        Self::write(self, bytes)?;
-        for place in buffer.iter_mut() {
-            match Self::read(self, true) {
-                Ok(v) => *place = v,
-                Err(e) => {
-                    res = Err(e);
-                    break;
-                }
-            }
-        }
-        Self::end_transmission(self);
-
-        res
+        Self::transaction(
+            self,
+            address,
+            &mut [Operation::Write(bytes), Operation::Read(buffer)],
+        )
     }
 }
@@ -11,5 +11,17 @@ pub const CPU_CYCLE_TIME_S: f32 = 110E-3f32 / MAX_CYCLE_TICKS as f32;
 pub const UART_BOUD: u32 = 9600;
 pub const CPU_SPEED_MHZ: u32 = 80;
 
-// write current work into eeptom every results 
-pub const UPDATE_EEPROM_EVERY: u32 = 10;
\ No newline at end of file
+// storage_task used to flush every 10 parsed frames; at the controller's
+// ~100ms reporting rate that's roughly a second, so keep the same cadence
+// now that the flush is driven by a timer instead of a frame counter.
+pub const STORAGE_FLUSH_PERIOD_MS: u64 = 1000;
+
+// generous enough to cover a slow flush cycle (storage append + display
+// refresh) without tripping on ordinary jitter, tight enough that a real
+// wedge resets the board well before the rider notices a frozen screen.
+pub const WATCHDOG_TIMEOUT_MS: u32 = 5000;
+
+// safe mode trusts nothing but the watchdog itself, so it just busy-waits
+// between feeds instead of pulling in a timer; picked to keep the feed
+// comfortably under WATCHDOG_TIMEOUT_MS at the CPU's clock speed.
+pub const SAFE_MODE_FEED_SPIN: u32 = 1_000_000;
\ No newline at end of file
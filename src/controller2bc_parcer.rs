@@ -12,6 +12,7 @@ pub struct Controller2BCParcer {
     raw_data: [u8; 12],
     wp: usize,
     end_timestamp: u32,
+    bad_frame_count: u32,
 }
 
 #[derive(Debug, FromPrimitive, Clone, Copy)]
@@ -94,31 +95,56 @@ impl Controller2BCParcer {
         if ok {
             self.raw_data[self.wp] = data;
             self.wp += 1;
+            return;
+        }
+
+        if self.wp != 0 {
+            // A byte was dropped or corrupted mid-frame, so the in-progress
+            // frame can never complete: resynchronize instead of leaving
+            // `wp` stuck waiting for a byte that will never come, by
+            // re-checking this same byte as a possible new frame start.
+            self.bad_frame_count = self.bad_frame_count.wrapping_add(1);
+            self.wp = 0;
+            if data == 0x41 {
+                self.raw_data[0] = data;
+                self.wp = 1;
+            }
         }
     }
 
     pub fn try_get(&mut self) -> Option<Message> {
-        if self.wp == self.raw_data.len() {
-            let res = Message {
-                bat_lvl: FromPrimitive::from_u8(self.raw_data[1]).unwrap_or_default(),
-                wheel_rotation_period: {
-                    let mut tmp = [0u8; core::mem::size_of::<u16>()];
-                    tmp.clone_from_slice(&self.raw_data[3..=4]);
-                    Milliseconds(u16::from_be_bytes(tmp) as u32)
-                },
-                error: FromPrimitive::from_u8(self.raw_data[5]).unwrap_or_default(),
-                crc: self.raw_data[6],
-                moving_mode: FromPrimitive::from_u8(self.raw_data[7]).unwrap_or_default(),
-                power: Watts(self.raw_data[8] as u32 * WATS_PER_UNIT),
-                motor_temperature: Celsius(self.raw_data[9] as i8),
-                end_timestamp: self.end_timestamp,
-            };
-            self.wp = 0;
+        if self.wp != self.raw_data.len() {
+            return None;
+        }
 
-            Some(res)
-        } else {
-            None
+        self.wp = 0;
+
+        if checksum(&self.raw_data) != self.raw_data[6] {
+            self.bad_frame_count = self.bad_frame_count.wrapping_add(1);
+            return None;
         }
+
+        Some(Message {
+            bat_lvl: FromPrimitive::from_u8(self.raw_data[1]).unwrap_or_default(),
+            wheel_rotation_period: {
+                let mut tmp = [0u8; core::mem::size_of::<u16>()];
+                tmp.clone_from_slice(&self.raw_data[3..=4]);
+                Milliseconds(u16::from_be_bytes(tmp) as u32)
+            },
+            error: FromPrimitive::from_u8(self.raw_data[5]).unwrap_or_default(),
+            crc: self.raw_data[6],
+            moving_mode: FromPrimitive::from_u8(self.raw_data[7]).unwrap_or_default(),
+            power: Watts(self.raw_data[8] as u32 * WATS_PER_UNIT),
+            motor_temperature: Celsius(self.raw_data[9] as i8),
+            end_timestamp: self.end_timestamp,
+        })
+    }
+
+    /// The number of frames dropped because of a misaligned stream or a
+    /// checksum mismatch, since this parser was created. Intended to be
+    /// logged periodically so silent desyncs on the UART link are visible.
+    pub fn bad_frame_count(&self) -> u32 {
+        self.bad_frame_count
     }
 
     #[allow(unused)]
@@ -137,6 +163,33 @@ impl Controller2BCParcer {
     }
 }
 
+/// Computes the controller's frame checksum over bytes 1..=5 and 7..=9 of
+/// the frame (everything but the leading 0x41, the checksum byte itself,
+/// and the two trailing zero bytes; the 0x30 at byte 2 is included).
+///
+/// A CRC-8 (poly 0x07, the common "CRC-8/SMBUS" variant) rather than a
+/// plain XOR fold: an XOR fold can't catch an even number of bit flips
+/// landing in the same byte position across different bytes, which a
+/// shift-register CRC does.
+fn checksum(raw_data: &[u8; 12]) -> u8 {
+    raw_data[1..6]
+        .iter()
+        .chain(raw_data[7..10].iter())
+        .fold(0u8, |crc, &b| crc_update(crc, b))
+}
+
+fn crc_update(crc: u8, byte: u8) -> u8 {
+    let mut crc = crc ^ byte;
+    for _ in 0..8 {
+        crc = if crc & 0x80 != 0 {
+            (crc << 1) ^ 0x07
+        } else {
+            crc << 1
+        };
+    }
+    crc
+}
+
 //-----------------------------------------------------------------------------
 
 impl Default for MovingMode {
@@ -108,6 +108,26 @@ where
         self.work_total
     }
 
+    /// Draws a static "safe mode" screen, used when repeated watchdog
+    /// resets mean we no longer trust the normal frame/chart rendering
+    /// path enough to re-enter it.
+    pub fn draw_safe_mode(&mut self) -> Result<(), display_interface::DisplayError> {
+        self.disp.clear();
+
+        Text::with_text_style(
+            "SAFE MODE",
+            Point::new(10, 28),
+            self.big_font,
+            TextStyleBuilder::new()
+                .alignment(embedded_graphics::text::Alignment::Left)
+                .baseline(Baseline::Top)
+                .build(),
+        )
+        .draw(&mut self.disp)?;
+
+        self.disp.flush()
+    }
+
     fn draw_progress_bar(&mut self, power: Watts) -> Result<(), display_interface::DisplayError> {
         let max_wigth = self.disp.dimensions().0 as u32;
         Rectangle::new(
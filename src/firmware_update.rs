@@ -0,0 +1,356 @@
+//! Signed over-the-UART firmware update.
+//!
+//! A new image arrives in framed chunks over UART0 (see [`UartFrameFeeder`])
+//! and is written into a staging flash region via
+//! [`FirmwareUpdater::write_chunk`]. Once the whole image has landed,
+//! [`FirmwareUpdater::finalize`] streams the staged image through SHA-512
+//! and checks a detached Ed25519 signature over the digest against
+//! [`UPDATE_PUBLIC_KEY`]; only a verified image is marked pending. On the
+//! next reset, [`try_swap_pending_update`] (run before the application
+//! proper starts) copies a pending staged image over the active slot and
+//! clears the pending flag, so a power loss mid-copy just resumes the copy
+//! on the following boot instead of bricking the board.
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use sha2::{Digest, Sha512};
+
+/// The public key baked into this firmware, hex-encoded and injected at
+/// build time via `ELBIKE_UPDATE_PUBLIC_KEY` (e.g. set in the release
+/// build's environment, never committed to source). There is no source
+/// fallback: an unset or malformed key fails the build instead of silently
+/// linking in a placeholder, since an unvalidated key (an all-zero one,
+/// for instance) decodes to a degenerate curve point that lets any
+/// signature verify against any image.
+const UPDATE_PUBLIC_KEY: [u8; 32] = match option_env!("ELBIKE_UPDATE_PUBLIC_KEY") {
+    Some(hex) => parse_public_key(hex),
+    None => panic!(
+        "ELBIKE_UPDATE_PUBLIC_KEY is not set; the real release key must be \
+         injected at build time, it cannot live in source"
+    ),
+};
+
+/// Parses a 64-character hex string into the 32 raw key bytes, at compile
+/// time. Panics (a build failure, not a runtime one) on anything else.
+const fn parse_public_key(hex: &str) -> [u8; 32] {
+    let hex = hex.as_bytes();
+    if hex.len() != 64 {
+        panic!("ELBIKE_UPDATE_PUBLIC_KEY must be exactly 64 hex characters");
+    }
+
+    const fn nibble(b: u8) -> u8 {
+        match b {
+            b'0'..=b'9' => b - b'0',
+            b'a'..=b'f' => b - b'a' + 10,
+            b'A'..=b'F' => b - b'A' + 10,
+            _ => panic!("ELBIKE_UPDATE_PUBLIC_KEY must be hex-encoded"),
+        }
+    }
+
+    let mut key = [0u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        key[i] = (nibble(hex[i * 2]) << 4) | nibble(hex[i * 2 + 1]);
+        i += 1;
+    }
+    key
+}
+
+/// How much of a flash page we buffer at a time while hashing or copying.
+const COPY_CHUNK: usize = 256;
+
+/// Where a staged update currently stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateState {
+    /// No update is staged.
+    Idle,
+    /// Chunks are being written into the staging region.
+    Staging,
+    /// A verified image is staged; the bootloader will swap it in on the
+    /// next reset.
+    PendingSwap,
+    /// The bootloader just swapped a staged image into the active slot;
+    /// the application should self-test before calling
+    /// [`FirmwareUpdater::mark_booted`].
+    JustSwapped,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Error {
+    /// `write_chunk` targeted a byte past the staging region.
+    OutOfRange,
+    /// The detached signature did not verify against the staged image.
+    BadSignature,
+}
+
+/// RTC "user memory" word used to persist [`UpdateState`] across resets,
+/// the same pattern [`crate::watchdog`]'s reset-streak counter uses (and a
+/// different word of the same region, so the two don't collide).
+const RTC_UPDATE_STATE_ADDR: *mut u32 = 0x6000_1204 as *mut u32;
+
+/// RTC word persisting the real staged-image length (`FirmwareUpdater`'s
+/// `written`) alongside the state above, so a bootloader-time swap copies
+/// exactly the bytes that were actually written instead of blindly
+/// assuming the whole staging region is live image data — a short image
+/// would otherwise have its erased (all-0xFF) staging tail overwrite the
+/// corresponding, still-good tail of the active image.
+const RTC_UPDATE_LEN_ADDR: *mut u32 = 0x6000_1208 as *mut u32;
+
+/// Reads the update state persisted by the previous boot. Only meaningful
+/// once at the very start of `main`, before [`try_swap_pending_update`]
+/// potentially advances it.
+pub fn load_persisted_state() -> UpdateState {
+    match unsafe { RTC_UPDATE_STATE_ADDR.read_volatile() } {
+        1 => UpdateState::Staging,
+        2 => UpdateState::PendingSwap,
+        3 => UpdateState::JustSwapped,
+        _ => UpdateState::Idle,
+    }
+}
+
+/// Persists `state` so it survives a reset (but not a cold power-on, like
+/// the rest of this RTC region).
+pub fn persist_state(state: UpdateState) {
+    let raw = match state {
+        UpdateState::Idle => 0,
+        UpdateState::Staging => 1,
+        UpdateState::PendingSwap => 2,
+        UpdateState::JustSwapped => 3,
+    };
+    unsafe { RTC_UPDATE_STATE_ADDR.write_volatile(raw) };
+}
+
+/// Reads the staged-image length persisted by the previous boot, paired
+/// with [`load_persisted_state`].
+pub fn load_persisted_len() -> u32 {
+    unsafe { RTC_UPDATE_LEN_ADDR.read_volatile() }
+}
+
+/// Persists the staged-image length so it survives a reset.
+pub fn persist_len(len: u32) {
+    unsafe { RTC_UPDATE_LEN_ADDR.write_volatile(len) };
+}
+
+/// Abstracts the staging and active flash regions so this module doesn't
+/// need to know about the ESP8266 flash map directly.
+pub trait UpdateFlash {
+    fn write_staging(&mut self, offset: u32, data: &[u8]);
+    fn read_staging(&mut self, offset: u32, buf: &mut [u8]);
+    fn erase_staging(&mut self);
+    fn staging_size(&self) -> u32;
+
+    fn copy_staging_to_active(&mut self, offset: u32, len: u32);
+    fn active_size(&self) -> u32;
+}
+
+pub struct FirmwareUpdater<F: UpdateFlash> {
+    flash: F,
+    written: u32,
+    state: UpdateState,
+}
+
+impl<F: UpdateFlash> FirmwareUpdater<F> {
+    /// `written` is the staged-image length persisted by a previous boot
+    /// (0 if none), so a `Staging`/`PendingSwap` carried across a reset
+    /// keeps knowing its real length instead of starting over at 0.
+    pub fn new(flash: F, state: UpdateState, written: u32) -> Self {
+        Self {
+            flash,
+            written,
+            state,
+        }
+    }
+
+    /// Writes one framed chunk of the new image into the staging region.
+    pub fn write_chunk(&mut self, offset: u32, data: &[u8]) -> Result<(), Error> {
+        if offset as u64 + data.len() as u64 > self.flash.staging_size() as u64 {
+            return Err(Error::OutOfRange);
+        }
+
+        self.flash.write_staging(offset, data);
+        self.written = self.written.max(offset + data.len() as u32);
+        self.state = UpdateState::Staging;
+        persist_state(self.state);
+        persist_len(self.written);
+
+        Ok(())
+    }
+
+    /// Hashes everything written so far with SHA-512 and verifies
+    /// `signature` against it. On success the image is marked pending for
+    /// the bootloader; on failure the staging region is erased so a half
+    /// or maliciously crafted image can't linger across a reset.
+    pub fn finalize(&mut self, signature: &[u8; 64]) -> Result<(), Error> {
+        let digest = self.hash_staged_image();
+
+        let key = VerifyingKey::from_bytes(&UPDATE_PUBLIC_KEY).map_err(|_| Error::BadSignature)?;
+        let signature = Signature::from_bytes(signature);
+
+        // `verify_strict` (not plain `verify`) additionally rejects
+        // small-order/cofactored signatures per RFC 8032, so a malformed or
+        // degenerate key/signature pair can't slip a forged image through.
+        if key.verify_strict(&digest, &signature).is_err() {
+            self.flash.erase_staging();
+            self.written = 0;
+            self.state = UpdateState::Idle;
+            persist_state(self.state);
+            persist_len(self.written);
+            return Err(Error::BadSignature);
+        }
+
+        self.state = UpdateState::PendingSwap;
+        persist_state(self.state);
+
+        Ok(())
+    }
+
+    fn hash_staged_image(&mut self) -> [u8; 64] {
+        let mut hasher = Sha512::new();
+        let mut buf = [0u8; COPY_CHUNK];
+        let mut offset = 0u32;
+
+        while offset < self.written {
+            let n = core::cmp::min(buf.len() as u32, self.written - offset) as usize;
+            self.flash.read_staging(offset, &mut buf[..n]);
+            hasher.update(&buf[..n]);
+            offset += n as u32;
+        }
+
+        hasher.finalize().into()
+    }
+
+    /// Returns whether a swap just happened, so the application can run a
+    /// self-test before trusting the new image.
+    pub fn get_state(&self) -> UpdateState {
+        self.state
+    }
+
+    /// Called by the application once a freshly swapped-in image has
+    /// passed its self-test.
+    pub fn mark_booted(&mut self) {
+        if self.state == UpdateState::JustSwapped {
+            self.state = UpdateState::Idle;
+            self.written = 0;
+            persist_state(self.state);
+            persist_len(self.written);
+        }
+    }
+}
+
+/// Run once at the very start of `main`, before anything else touches the
+/// flash: if a verified image is pending, copies it over the active slot
+/// and clears the pending flag, returning the state the application
+/// should continue with. A reset mid-copy just re-enters this function
+/// and resumes, since the pending flag isn't cleared until the copy is
+/// complete.
+///
+/// `written` is the real staged-image length, as persisted by
+/// [`persist_len`] — only that many bytes are copied, so an image
+/// shorter than the staging region doesn't drag its erased (all-0xFF)
+/// tail over the still-good tail of the active image.
+pub fn try_swap_pending_update<F: UpdateFlash>(
+    flash: &mut F,
+    state: UpdateState,
+    written: u32,
+) -> UpdateState {
+    if state != UpdateState::PendingSwap {
+        return state;
+    }
+
+    let len = core::cmp::min(written, core::cmp::min(flash.staging_size(), flash.active_size()));
+    flash.copy_staging_to_active(0, len);
+    flash.erase_staging();
+
+    persist_state(UpdateState::JustSwapped);
+    UpdateState::JustSwapped
+}
+
+/// How many staged-image bytes a single [`UpdateEvent::Chunk`] carries.
+const CHUNK_DATA_LEN: usize = 16;
+
+/// First byte of a chunk-write frame: `[0x55, offset: u32 LE, data: [u8;
+/// CHUNK_DATA_LEN], checksum]`.
+const CHUNK_MARKER: u8 = 0x55;
+/// First byte of a finalize frame: `[0x56, signature: [u8; 64], checksum]`.
+const FINALIZE_MARKER: u8 = 0x56;
+
+const CHUNK_FRAME_LEN: usize = 1 + 4 + CHUNK_DATA_LEN + 1;
+const FINALIZE_FRAME_LEN: usize = 1 + 64 + 1;
+
+/// A parsed update frame, ready to feed straight into [`FirmwareUpdater`].
+pub enum UpdateEvent {
+    Chunk { offset: u32, data: [u8; CHUNK_DATA_LEN] },
+    Finalize([u8; 64]),
+}
+
+/// Byte-at-a-time parser for update frames arriving on the same UART0 link
+/// as the controller frames (distinguished by their leading marker byte,
+/// which never collides with `Controller2BCParcer`'s `0x41`). Modeled on
+/// that parser's `feed`/`try_get` split so the UART ISR can drive both
+/// from the same byte stream.
+#[derive(Default)]
+pub struct UartFrameFeeder {
+    buf: [u8; FINALIZE_FRAME_LEN],
+    wp: usize,
+    frame_len: usize,
+}
+
+impl UartFrameFeeder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8; FINALIZE_FRAME_LEN],
+            wp: 0,
+            frame_len: 0,
+        }
+    }
+
+    pub fn feed(&mut self, data: u8) {
+        if self.wp == 0 {
+            self.frame_len = match data {
+                CHUNK_MARKER => CHUNK_FRAME_LEN,
+                FINALIZE_MARKER => FINALIZE_FRAME_LEN,
+                // Not a recognized frame start (could be a controller
+                // frame byte instead): ignore it and keep waiting.
+                _ => return,
+            };
+        }
+
+        self.buf[self.wp] = data;
+        self.wp += 1;
+    }
+
+    pub fn try_get(&mut self) -> Option<UpdateEvent> {
+        if self.frame_len == 0 || self.wp != self.frame_len {
+            return None;
+        }
+
+        let frame_len = self.frame_len;
+        self.wp = 0;
+        self.frame_len = 0;
+
+        let checksum = self.buf[..frame_len - 1]
+            .iter()
+            .fold(0u8, |acc, b| acc ^ b);
+        if checksum != self.buf[frame_len - 1] {
+            return None;
+        }
+
+        match self.buf[0] {
+            CHUNK_MARKER => {
+                let mut offset_bytes = [0u8; 4];
+                offset_bytes.copy_from_slice(&self.buf[1..5]);
+                let mut data = [0u8; CHUNK_DATA_LEN];
+                data.copy_from_slice(&self.buf[5..5 + CHUNK_DATA_LEN]);
+                Some(UpdateEvent::Chunk {
+                    offset: u32::from_le_bytes(offset_bytes),
+                    data,
+                })
+            }
+            FINALIZE_MARKER => {
+                let mut signature = [0u8; 64];
+                signature.copy_from_slice(&self.buf[1..65]);
+                Some(UpdateEvent::Finalize(signature))
+            }
+            _ => None,
+        }
+    }
+}
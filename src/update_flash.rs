@@ -0,0 +1,149 @@
+//! Concrete [`firmware_update::UpdateFlash`] backed by the ESP8266's SPI
+//! flash, split into the active image region and a staging region that
+//! follows it.
+//!
+//! The chip has no DMA-free "write arbitrary flash" HAL exposed through
+//! `esp8266_hal`, so this calls straight into the boot ROM's SPI flash
+//! routines the way the vendor SDK itself does (`Cache_Read_Disable`
+//! around any write/erase, since the flash can't be read-mapped and
+//! written at the same time). The exact ROM entry addresses are only
+//! documented informally (they've been stable across the 8266's lifetime,
+//! but aren't part of any public header) and the exact offsets of the
+//! staging/active regions depend on the board's flash size and the linker
+//! script's reserved space, so both are marked below for whoever brings
+//! up a new board revision.
+
+use esp8266_hal::ram;
+
+use crate::firmware_update::UpdateFlash;
+
+// TODO: confirm against this board's flash size map; these assume a 1MB
+// part with a 64KB staging region carved out just past the application
+// image, matching the linker script's reserved `rodata` tail.
+const STAGING_OFFSET: u32 = 0x0008_0000;
+const STAGING_SIZE: u32 = 0x0001_0000;
+const ACTIVE_OFFSET: u32 = 0x0000_0000;
+const ACTIVE_SIZE: u32 = 0x0008_0000;
+
+// Boot ROM entry points, called the same way the vendor SDK's
+// `spi_flash_*` wrappers do. Not part of any public header; addresses
+// taken from the commonly documented ESP8266 ROM layout.
+const ROM_SPI_READ: usize = 0x4000_4b1c;
+const ROM_SPI_WRITE: usize = 0x4000_4b40;
+const ROM_SPI_ERASE_SECTOR: usize = 0x4000_4ab0;
+
+// The icache maps this same flash at 0x4020_0000+ for code fetch
+// (including the code in this file, whenever it overwrites the running
+// image at `ACTIVE_OFFSET`), so every raw flash access below brackets
+// itself with these instead of racing instruction fetch against the SPI
+// controller. Also informally documented rather than header-exposed.
+const ROM_CACHE_READ_DISABLE: usize = 0x4000_04f8;
+const ROM_CACHE_READ_ENABLE: usize = 0x4000_0520;
+
+const SECTOR_SIZE: u32 = 0x1000;
+
+type SpiReadWriteFn = unsafe extern "C" fn(addr: u32, buf: *mut u8, size: u32) -> i32;
+type SpiEraseSectorFn = unsafe extern "C" fn(sector: u32) -> i32;
+type CacheReadDisableFn = unsafe extern "C" fn();
+type CacheReadEnableFn = unsafe extern "C" fn(map: u8, p: u8, v: u8);
+
+pub struct EspSpiFlash;
+
+impl EspSpiFlash {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Runs `body` with the icache unmapped from flash, as every call into
+    /// the ROM's SPI read/write/erase routines below must: the 8266 can't
+    /// fetch instructions from flash and have the SPI controller drive the
+    /// same flash at once, and this code itself lives in that flash until
+    /// `Cache_Read_Enable` remaps it back in.
+    #[ram]
+    fn with_cache_disabled<T>(body: impl FnOnce() -> T) -> T {
+        let cache_read_disable: CacheReadDisableFn =
+            unsafe { core::mem::transmute(ROM_CACHE_READ_DISABLE) };
+        let cache_read_enable: CacheReadEnableFn =
+            unsafe { core::mem::transmute(ROM_CACHE_READ_ENABLE) };
+
+        unsafe { cache_read_disable() };
+        let result = body();
+        unsafe { cache_read_enable(0, 0, 0) };
+
+        result
+    }
+
+    #[ram]
+    fn read(&self, offset: u32, buf: &mut [u8]) {
+        Self::with_cache_disabled(|| {
+            let rom_read: SpiReadWriteFn = unsafe { core::mem::transmute(ROM_SPI_READ) };
+            unsafe { rom_read(offset, buf.as_mut_ptr(), buf.len() as u32) };
+        });
+    }
+
+    #[ram]
+    fn write(&mut self, offset: u32, data: &[u8]) {
+        Self::with_cache_disabled(|| {
+            let rom_write: SpiReadWriteFn = unsafe { core::mem::transmute(ROM_SPI_WRITE) };
+            unsafe { rom_write(offset, data.as_ptr() as *mut u8, data.len() as u32) };
+        });
+    }
+
+    #[ram]
+    fn erase_range(&mut self, offset: u32, len: u32) {
+        Self::with_cache_disabled(|| {
+            let rom_erase: SpiEraseSectorFn =
+                unsafe { core::mem::transmute(ROM_SPI_ERASE_SECTOR) };
+            let first_sector = offset / SECTOR_SIZE;
+            let sector_count = len.div_ceil(SECTOR_SIZE);
+            for sector in first_sector..first_sector + sector_count {
+                unsafe { rom_erase(sector) };
+            }
+        });
+    }
+}
+
+impl Default for EspSpiFlash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UpdateFlash for EspSpiFlash {
+    #[ram]
+    fn write_staging(&mut self, offset: u32, data: &[u8]) {
+        self.write(STAGING_OFFSET + offset, data);
+    }
+
+    #[ram]
+    fn read_staging(&mut self, offset: u32, buf: &mut [u8]) {
+        self.read(STAGING_OFFSET + offset, buf);
+    }
+
+    #[ram]
+    fn erase_staging(&mut self) {
+        self.erase_range(STAGING_OFFSET, STAGING_SIZE);
+    }
+
+    fn staging_size(&self) -> u32 {
+        STAGING_SIZE
+    }
+
+    #[ram]
+    fn copy_staging_to_active(&mut self, offset: u32, len: u32) {
+        self.erase_range(ACTIVE_OFFSET + offset, len);
+
+        let mut buf = [0u8; 256];
+        let mut done = 0u32;
+        while done < len {
+            let n = core::cmp::min(buf.len() as u32, len - done) as usize;
+            self.read(STAGING_OFFSET + offset + done, &mut buf[..n]);
+            self.write(ACTIVE_OFFSET + offset + done, &buf[..n]);
+            done += n as u32;
+        }
+    }
+
+    fn active_size(&self) -> u32 {
+        ACTIVE_SIZE
+    }
+}
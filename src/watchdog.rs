@@ -0,0 +1,101 @@
+//! Hardware watchdog with staged health gating.
+//!
+//! The watchdog is only fed from the main control path once a full
+//! healthy cycle has actually completed, so a wedge anywhere along it (a
+//! stuck software-I2C transaction, an SSD1306 flush that never returns, a
+//! UART parser that's stopped producing frames) costs the chip a reset
+//! instead of leaving the rider staring at a frozen display. If resets
+//! keep repeating, [`record_boot`]'s returned count lets `main` give up
+//! on the normal path and fall back to a minimal safe display instead of
+//! re-entering whatever wedged it last time.
+
+use esp8266_hal::target::WDT;
+
+/// How many consecutive watchdog-triggered resets we tolerate before
+/// falling back to safe mode.
+pub const MAX_CONSECUTIVE_WATCHDOG_RESETS: u8 = 3;
+
+/// Raw RTC "user memory" word used to persist the watchdog-reset streak
+/// across resets (this region survives everything except a cold
+/// power-on). The exact offset is SDK/board specific; this one matches
+/// the slot the vendor SDK leaves free for application use.
+const RTC_RESET_COUNTER_ADDR: *mut u32 = 0x6000_1200 as *mut u32;
+
+/// Why the chip last came out of reset, as read from the RTC
+/// reset-reason register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetReason {
+    PowerOn,
+    ExternalReset,
+    Watchdog,
+    SoftRestart,
+    DeepSleepAwake,
+    Other(u8),
+}
+
+impl ResetReason {
+    fn from_raw(code: u8) -> Self {
+        match code {
+            0 => Self::PowerOn,
+            1 => Self::ExternalReset,
+            2 => Self::Watchdog,
+            4 => Self::SoftRestart,
+            5 => Self::DeepSleepAwake,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// Reads the RTC "reset reason" register. Only meaningful right at boot,
+/// before anything else touches the RTC peripheral.
+pub fn last_reset_reason() -> ResetReason {
+    // TODO: the exact register/bit layout depends on which ROM the board
+    // shipped with; this reads the commonly documented reset-cause byte.
+    let raw = unsafe { (*esp8266_hal::target::RTC::ptr()).reset_reason.read().bits() as u8 };
+    ResetReason::from_raw(raw)
+}
+
+/// Updates the persisted watchdog-reset streak for this boot and returns
+/// the new count (0 if this boot wasn't caused by the watchdog).
+pub fn record_boot(reason: ResetReason) -> u8 {
+    let count = if reason == ResetReason::Watchdog {
+        unsafe { RTC_RESET_COUNTER_ADDR.read_volatile() as u8 }.saturating_add(1)
+    } else {
+        0
+    };
+    unsafe { RTC_RESET_COUNTER_ADDR.write_volatile(count as u32) };
+    count
+}
+
+/// Drives the ESP8266 hardware watchdog.
+pub struct Watchdog {
+    wdt: WDT,
+}
+
+impl Watchdog {
+    pub fn new(wdt: WDT) -> Self {
+        Self { wdt }
+    }
+
+    pub fn start(&mut self, timeout_ms: u32) {
+        unsafe {
+            self.wdt.wdtconfig1.write(|w| w.bits(timeout_ms));
+            self.wdt.wdtconfig0.write(|w| w.wdt_en().set_bit());
+        }
+    }
+
+    pub fn feed(&mut self) {
+        unsafe {
+            self.wdt.wdtfeed.write(|w| w.bits(1));
+        }
+    }
+
+    /// Used by the firmware-update path: a multi-second flash erase/write
+    /// can't realistically keep up with the control path's feed cadence,
+    /// so it disarms the watchdog for its duration instead of racing it.
+    pub fn disarm(&mut self) {
+        unsafe {
+            self.wdt.wdtconfig0.write(|w| w.wdt_en().clear_bit());
+        }
+    }
+}